@@ -36,6 +36,21 @@
 // pages are variable size
 const SIZE_CLASSES: usize = 6;
 
+// virtual address space reserved up front for the heap file mapping, so
+// growing the heap never moves its base address and never invalidates
+// already-swizzled `Pointer`s. this is reserved, not committed: the OS
+// doesn't back it with real memory/storage until the live prefix grows
+// into it.
+const RESERVED_HEAP_BYTES: usize = 64 * 1024 * 1024 * 1024;
+
+// PageTable starts with 2^4 = 16 buckets and re-shards by doubling as
+// the id space grows
+const INITIAL_PAGE_TABLE_BUCKETS_POW2: u32 = 4;
+
+// used only to size the w-TinyLFU cache's entry-count capacity from a
+// byte budget; actual pages are variable size
+const ESTIMATED_PAGE_SIZE: usize = 4096;
+
 type PageId = u64;
 type TxId = u64;
 type Lsn = u64;
@@ -44,13 +59,84 @@ use {
     crc32fast::Hasher,
     libc::{mmap, munmap},
     std::{
+        collections::VecDeque,
         convert::TryFrom,
         fs::{File, OpenOptions},
-        io::Write,
+        hash::{Hash, Hasher as StdHasher},
+        io::{Read, Seek, SeekFrom, Write},
+        ops::Deref,
         path::Path,
+        sync::Arc,
     },
 };
 
+/// A guard over a value's bytes, sharing the cached page's `Arc<[u8]>`
+/// rather than copying the value out.
+struct ValueRef {
+    page: Arc<[u8]>,
+    // byte range of the value within `page`
+    start: usize,
+    end: usize,
+}
+
+impl Deref for ValueRef {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.page[self.start..self.end]
+    }
+}
+
+/// Codec applied to a page's `[keys | values]` region before it is
+/// written into a size-classed buffer pool arena. Chosen per-`Db` at
+/// open time.
+///
+/// `Lz4`/`Miniz` aren't implemented: they'd mean depending on the `lz4`
+/// and `miniz_oxide` crates, and this snapshot has no Cargo.toml to
+/// declare that dependency in (see the crate root's module doc). Rather
+/// than expose variants whose `compress`/`decompress` panic on a
+/// perfectly valid `open_with_compression` call, only `None` is
+/// constructible for now; re-add the other variants once those crates
+/// are actually available.
+///
+/// The key/value length directory is always left uncompressed, so range
+/// scans can still binary search for boundaries without paying for a
+/// full decompression where the directory alone is enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompressionType {
+    None,
+}
+
+impl CompressionType {
+    // one-byte tag stored in the page header
+    const fn tag(&self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+        }
+    }
+
+    // inverse of `tag`, used when fault-in only has the header's tag
+    // byte to go on
+    fn from_tag(tag: u8) -> CompressionType {
+        match tag {
+            0 => CompressionType::None,
+            other => panic!("unknown compression tag {}", other),
+        }
+    }
+
+    fn compress(&self, raw: &[u8]) -> Vec<u8> {
+        match self {
+            CompressionType::None => raw.to_vec(),
+        }
+    }
+
+    fn decompress(&self, compressed: &[u8], uncompressed_len: usize) -> Vec<u8> {
+        match self {
+            CompressionType::None => compressed.to_vec(),
+        }
+    }
+}
+
 struct Pointer([u8; 8]);
 
 struct Leaf<'a> {
@@ -67,25 +153,70 @@ struct Index<'a> {
 struct Page {
     // [header | key lengths | value lengths | keys | values]
     //
+    // the "keys | values" region may be compressed; the length
+    // directory ahead of it never is, so scans can still find
+    // boundaries without decompressing.
+    //
     // header: {
     //  is leaf: 1 byte,
     //  number of children: 3 bytes,
-    //  key length sum: 4 bytes
+    //  key length sum: 4 bytes,
+    //  codec: 1 byte (see CompressionType::tag),
+    //  uncompressed length of [keys | values]: 4 bytes,
+    //  CRC32 of everything from the key lengths onward: 4 bytes,
     data: [u8],
 }
 
+/// Errors surfaced once a page or log record fails the checksum it was
+/// written with, instead of handing back bytes that silently bit-rotted
+/// in the mmapped heap or log files.
+#[derive(Debug)]
+enum DbError {
+    Corruption { pid: PageId, expected: u32, actual: u32 },
+}
+
 #[derive(Debug)]
 struct PageView<'a> {
     is_leaf: bool,
     child_count: usize,
     lo: &'a [u8],
     hi: &'a [u8],
-    keys: &'a [&'a [u8]],
-    values: &'a [&'a [u8]],
+    // key_lengths[0] and [1] are lo/hi's lengths, key_lengths[2..] and
+    // val_lengths are the per-child directory; never compressed, so
+    // callers can use these without touching `payload` at all.
+    //
+    // owned rather than borrowed: the directory starts at byte offset 17
+    // into the page, which isn't guaranteed 8-byte aligned, so it can't
+    // be read as a `&[u64]` without risking unaligned-access UB.
+    key_lengths: Vec<u64>,
+    val_lengths: Vec<u64>,
+    // the codec this page was written with, and its decompressed
+    // [keys | values] bytes
+    codec: CompressionType,
+    payload: Vec<u8>,
+}
+
+// reads a little-endian u64 out of a byte slice at an arbitrary (possibly
+// unaligned) offset, one byte at a time instead of casting a pointer to
+// `*const u64` and dereferencing it
+fn read_u64_le(bytes: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+// same as `read_u64_le`, for the u32 length prefixes in the log's wire
+// format
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buf)
 }
 
 impl Page {
-    const fn view(&self) -> PageView<'_> {
+    fn view(&self, pid: PageId) -> Result<PageView<'_>, DbError> {
+        self.check_checksum(pid)?;
+
         let is_leaf = self.data[0] == 0;
 
         // does not account for lo and hi keys
@@ -100,29 +231,57 @@ impl Page {
             self.data[7],
         ]) as usize;
 
-        let key_length_base = unsafe { self.data.as_ptr().add(5) };
-        let val_length_base =
-            unsafe { key_length_base.add((2 * 8) + (child_count * 8)) };
-        let keys_base = unsafe { val_length_base.add(child_count * 8) };
-        let val_base = unsafe { keys_base.add(key_length_sum) };
+        let codec = CompressionType::from_tag(self.data[8]);
+        let uncompressed_len = u32::from_le_bytes([
+            self.data[9],
+            self.data[10],
+            self.data[11],
+            self.data[12],
+        ]) as usize;
 
-        let key_lengths: &[u64] = unsafe {
-            std::mem::transmute((key_length_base as *mut u64, child_count + 2))
-        };
+        // 17 is not 8-byte aligned in general (the page's backing bytes
+        // live at an arbitrary offset into an mmap'd arena), so these
+        // directories are read field-by-field via `from_le_bytes`
+        // instead of being transmuted into `&[u64]` slices
+        let key_length_base_offset = 17;
+        let val_length_base_offset = key_length_base_offset + (2 + child_count) * 8;
 
-        let val_lengths: &[u64] = unsafe {
-            std::mem::transmute((val_length_base as *mut u64, child_count))
-        };
+        let key_lengths: Vec<u64> = (0..child_count + 2)
+            .map(|i| read_u64_le(&self.data, key_length_base_offset + i * 8))
+            .collect();
+
+        let val_lengths: Vec<u64> = (0..child_count)
+            .map(|i| read_u64_le(&self.data, val_length_base_offset + i * 8))
+            .collect();
 
         let lo_len = key_lengths[0] as usize;
         let hi_len = key_lengths[1] as usize;
 
+        // keys_base..end of the page is `codec`-compressed whenever
+        // codec != CompressionType::None; decompress it now so a
+        // future key/value-slicing pass has real bytes to work from
+        // instead of computing codec/uncompressed_len and dropping them
+        let keys_base_offset =
+            17 + (2 + child_count) * 8 + child_count * 8;
+        let payload =
+            codec.decompress(&self.data[keys_base_offset..], uncompressed_len);
+
+        // the lo/hi boundary keys live at the front of `payload` once
+        // decompressed, ahead of the per-child keys; slicing them out is
+        // still unimplemented, same as the commented-out accessors below
         let lo = &[];
         let hi = &[];
-        let keys = &[];
-        let values = &[];
 
-        PageView { is_leaf, child_count, hi, lo, keys, values }
+        Ok(PageView {
+            is_leaf,
+            child_count,
+            hi,
+            lo,
+            key_lengths,
+            val_lengths,
+            codec,
+            payload,
+        })
     }
 
     /*
@@ -166,6 +325,38 @@ impl Page {
     fn remove(&self) -> &[u8] {
         todo!()
     }
+
+    // the checksum stored in the header, over [17..]
+    fn stored_checksum(&self) -> u32 {
+        u32::from_le_bytes([
+            self.data[13],
+            self.data[14],
+            self.data[15],
+            self.data[16],
+        ])
+    }
+
+    // recomputed over the length directory plus the (possibly
+    // compressed) keys/values region, i.e. everything after the
+    // checksum field itself
+    fn compute_checksum(&self) -> u32 {
+        let mut hasher = Hasher::new();
+        hasher.update(&self.data[17..]);
+        hasher.finalize()
+    }
+
+    /// Verifies this page's CRC32 on fault-in, surfacing a typed
+    /// `Corruption` error instead of handing back bytes that silently
+    /// bit-rotted in the mmapped heap.
+    fn check_checksum(&self, pid: PageId) -> Result<(), DbError> {
+        let expected = self.stored_checksum();
+        let actual = self.compute_checksum();
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(DbError::Corruption { pid, expected, actual })
+        }
+    }
 }
 
 enum PageUpdate<'a> {
@@ -181,42 +372,807 @@ enum LogRecord<'a> {
         redo: PageUpdate<'a>,
         undo: PageUpdate<'a>,
         previous_lsn: Lsn,
+        // CRC32 over this record's payload, verified when it's read
+        // back during log replay
+        checksum: u32,
     },
     Commit {
         tx: TxId,
         last_lsn: Lsn,
+        checksum: u32,
     },
+    /// Compensation log record, written while undoing a loser
+    /// transaction; `undo_next_lsn` lets a repeated crash mid-undo
+    /// resume without re-applying this undo.
+    Clr {
+        lsn: Lsn,
+        tx: TxId,
+        pid: PageId,
+        undo: PageUpdate<'a>,
+        undo_next_lsn: Lsn,
+        checksum: u32,
+    },
+    /// A fuzzy checkpoint: a snapshot of the dirty page table and
+    /// transaction table taken without stalling writers. Recovery's
+    /// analysis pass can start from the most recent checkpoint's `lsn`
+    /// instead of scanning the whole log, making that `lsn` the
+    /// "lowest stable point" `BufferPool::open` looks for.
+    Checkpoint {
+        lsn: Lsn,
+        dirty_page_table: Vec<(PageId, Lsn)>,
+        transaction_table: Vec<(TxId, Lsn, bool)>,
+        checksum: u32,
+    },
+}
+
+/// Owned counterpart to `PageUpdate`, used once a log record has been
+/// read back off disk and no longer borrows from an in-flight write.
+#[derive(Clone)]
+enum PageUpdateOwned {
+    Set { key: Vec<u8>, value: Vec<u8> },
+    Del { key: Vec<u8> },
+}
+
+impl PageUpdateOwned {
+    // appends this update's wire form to `buf`: a one-byte tag
+    // distinguishing Set/Del, then each field length-prefixed
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            PageUpdateOwned::Set { key, value } => {
+                buf.push(0);
+                buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                buf.extend_from_slice(key);
+                buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                buf.extend_from_slice(value);
+            }
+            PageUpdateOwned::Del { key } => {
+                buf.push(1);
+                buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                buf.extend_from_slice(key);
+            }
+        }
+    }
+
+    // inverse of `write_to`: parses one PageUpdate out of `buf` starting
+    // at `offset`, returning it alongside the offset just past it
+    fn read_from(buf: &[u8], offset: usize) -> (PageUpdateOwned, usize) {
+        let tag = buf[offset];
+        let offset = offset + 1;
+
+        let key_len = read_u32_le(buf, offset) as usize;
+        let offset = offset + 4;
+        let key = buf[offset..offset + key_len].to_vec();
+        let offset = offset + key_len;
+
+        match tag {
+            0 => {
+                let val_len = read_u32_le(buf, offset) as usize;
+                let offset = offset + 4;
+                let value = buf[offset..offset + val_len].to_vec();
+                let offset = offset + val_len;
+                (PageUpdateOwned::Set { key, value }, offset)
+            }
+            1 => (PageUpdateOwned::Del { key }, offset),
+            other => panic!("unknown PageUpdate tag {}", other),
+        }
+    }
+}
+
+/// Owned counterpart to `LogRecord`, produced by deserializing bytes
+/// read from the log file during recovery.
+enum LogRecordOwned {
+    Update {
+        lsn: Lsn,
+        tx: TxId,
+        pid: PageId,
+        redo: PageUpdateOwned,
+        undo: PageUpdateOwned,
+        previous_lsn: Lsn,
+        checksum: u32,
+    },
+    Commit {
+        tx: TxId,
+        last_lsn: Lsn,
+        checksum: u32,
+    },
+    Clr {
+        lsn: Lsn,
+        tx: TxId,
+        pid: PageId,
+        undo: PageUpdateOwned,
+        undo_next_lsn: Lsn,
+        checksum: u32,
+    },
+    Checkpoint {
+        lsn: Lsn,
+        dirty_page_table: Vec<(PageId, Lsn)>,
+        transaction_table: Vec<(TxId, Lsn, bool)>,
+        checksum: u32,
+    },
+}
+
+impl LogRecordOwned {
+    fn lsn(&self) -> Lsn {
+        match self {
+            LogRecordOwned::Update { lsn, .. }
+            | LogRecordOwned::Clr { lsn, .. }
+            | LogRecordOwned::Checkpoint { lsn, .. } => *lsn,
+            LogRecordOwned::Commit { last_lsn, .. } => *last_lsn,
+        }
+    }
+
+    // one-byte tag identifying the variant, stored ahead of its fields
+    fn tag(&self) -> u8 {
+        match self {
+            LogRecordOwned::Update { .. } => 0,
+            LogRecordOwned::Commit { .. } => 1,
+            LogRecordOwned::Clr { .. } => 2,
+            LogRecordOwned::Checkpoint { .. } => 3,
+        }
+    }
+
+    fn stored_checksum(&self) -> u32 {
+        match self {
+            LogRecordOwned::Update { checksum, .. }
+            | LogRecordOwned::Commit { checksum, .. }
+            | LogRecordOwned::Clr { checksum, .. }
+            | LogRecordOwned::Checkpoint { checksum, .. } => *checksum,
+        }
+    }
+
+    // every field except the checksum itself, tag-prefixed; this is
+    // what the checksum is computed over, mirroring Page::compute_checksum
+    fn payload_for_checksum(&self) -> Vec<u8> {
+        let mut buf = vec![self.tag()];
+        match self {
+            LogRecordOwned::Update {
+                lsn, tx, pid, redo, undo, previous_lsn, ..
+            } => {
+                buf.extend_from_slice(&lsn.to_le_bytes());
+                buf.extend_from_slice(&tx.to_le_bytes());
+                buf.extend_from_slice(&pid.to_le_bytes());
+                redo.write_to(&mut buf);
+                undo.write_to(&mut buf);
+                buf.extend_from_slice(&previous_lsn.to_le_bytes());
+            }
+            LogRecordOwned::Commit { tx, last_lsn, .. } => {
+                buf.extend_from_slice(&tx.to_le_bytes());
+                buf.extend_from_slice(&last_lsn.to_le_bytes());
+            }
+            LogRecordOwned::Clr {
+                lsn, tx, pid, undo, undo_next_lsn, ..
+            } => {
+                buf.extend_from_slice(&lsn.to_le_bytes());
+                buf.extend_from_slice(&tx.to_le_bytes());
+                buf.extend_from_slice(&pid.to_le_bytes());
+                undo.write_to(&mut buf);
+                buf.extend_from_slice(&undo_next_lsn.to_le_bytes());
+            }
+            LogRecordOwned::Checkpoint {
+                lsn,
+                dirty_page_table,
+                transaction_table,
+                ..
+            } => {
+                buf.extend_from_slice(&lsn.to_le_bytes());
+                buf.extend_from_slice(&(dirty_page_table.len() as u32).to_le_bytes());
+                for (pid, lsn) in dirty_page_table {
+                    buf.extend_from_slice(&pid.to_le_bytes());
+                    buf.extend_from_slice(&lsn.to_le_bytes());
+                }
+                buf.extend_from_slice(
+                    &(transaction_table.len() as u32).to_le_bytes(),
+                );
+                for (tx, lsn, committed) in transaction_table {
+                    buf.extend_from_slice(&tx.to_le_bytes());
+                    buf.extend_from_slice(&lsn.to_le_bytes());
+                    buf.push(*committed as u8);
+                }
+            }
+        }
+        buf
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let mut hasher = Hasher::new();
+        hasher.update(&self.payload_for_checksum());
+        hasher.finalize()
+    }
+
+    fn verify_checksum(&self) -> bool {
+        self.compute_checksum() == self.stored_checksum()
+    }
+
+    // length-prefixed wire form, so a reader can frame one record at a
+    // time without parsing its variant-specific payload first
+    fn serialize(&self) -> Vec<u8> {
+        let payload = self.payload_for_checksum();
+        let checksum = self.compute_checksum();
+
+        let mut buf = Vec::with_capacity(4 + payload.len() + 4);
+        buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&payload);
+        buf.extend_from_slice(&checksum.to_le_bytes());
+        buf
+    }
+
+    // inverse of `payload_for_checksum`: parses one record's fields out
+    // of an already-framed, already checksum-verified payload. Unknown
+    // tags return None rather than panicking, since a future record
+    // format shouldn't make an older recovery implementation crash.
+    fn deserialize(payload: &[u8], checksum: u32) -> Option<LogRecordOwned> {
+        let tag = payload[0];
+        let offset = 1;
+
+        match tag {
+            0 => {
+                let lsn = read_u64_le(payload, offset);
+                let tx = read_u64_le(payload, offset + 8);
+                let pid = read_u64_le(payload, offset + 16);
+                let (redo, offset) = PageUpdateOwned::read_from(payload, offset + 24);
+                let (undo, offset) = PageUpdateOwned::read_from(payload, offset);
+                let previous_lsn = read_u64_le(payload, offset);
+                Some(LogRecordOwned::Update {
+                    lsn,
+                    tx,
+                    pid,
+                    redo,
+                    undo,
+                    previous_lsn,
+                    checksum,
+                })
+            }
+            1 => {
+                let tx = read_u64_le(payload, offset);
+                let last_lsn = read_u64_le(payload, offset + 8);
+                Some(LogRecordOwned::Commit { tx, last_lsn, checksum })
+            }
+            2 => {
+                let lsn = read_u64_le(payload, offset);
+                let tx = read_u64_le(payload, offset + 8);
+                let pid = read_u64_le(payload, offset + 16);
+                let (undo, offset) = PageUpdateOwned::read_from(payload, offset + 24);
+                let undo_next_lsn = read_u64_le(payload, offset);
+                Some(LogRecordOwned::Clr { lsn, tx, pid, undo, undo_next_lsn, checksum })
+            }
+            3 => {
+                let lsn = read_u64_le(payload, offset);
+                let offset = offset + 8;
+
+                let dpt_len = read_u32_le(payload, offset) as usize;
+                let mut offset = offset + 4;
+                let mut dirty_page_table = Vec::with_capacity(dpt_len);
+                for _ in 0..dpt_len {
+                    let pid = read_u64_le(payload, offset);
+                    let lsn = read_u64_le(payload, offset + 8);
+                    dirty_page_table.push((pid, lsn));
+                    offset += 16;
+                }
+
+                let tt_len = read_u32_le(payload, offset) as usize;
+                let mut offset = offset + 4;
+                let mut transaction_table = Vec::with_capacity(tt_len);
+                for _ in 0..tt_len {
+                    let tx = read_u64_le(payload, offset);
+                    let last_lsn = read_u64_le(payload, offset + 8);
+                    let committed = payload[offset + 16] != 0;
+                    transaction_table.push((tx, last_lsn, committed));
+                    offset += 17;
+                }
+
+                Some(LogRecordOwned::Checkpoint {
+                    lsn,
+                    dirty_page_table,
+                    transaction_table,
+                    checksum,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A `PageId` -> heap-pointer directory sharded into `2^capacity_pow2`
+/// independently growable buckets, selected by the high bits of the
+/// `PageId`. This keeps the directory "shared-nothing": each bucket
+/// owns a disjoint region of the id space, so threads/cores working
+/// different buckets never contend, and growing the table re-shards by
+/// doubling the bucket count (one more high bit) rather than
+/// reallocating one global `Vec`.
+#[derive(Debug)]
+struct PageTable {
+    // there are 2^capacity_pow2 buckets; capacity_pow2 only ever grows
+    capacity_pow2: u32,
+    buckets: Vec<PageTableBucket>,
+    // rotates across buckets on allocation so occupancy stays balanced
+    next_bucket: usize,
+}
+
+#[derive(Debug, Default)]
+struct PageTableBucket {
+    // offset within the bucket -> heap pointer; None where unallocated
+    pointers: Vec<Option<usize>>,
+}
+
+impl PageTable {
+    fn new(capacity_pow2: u32) -> PageTable {
+        let num_buckets = 1usize << capacity_pow2;
+        PageTable {
+            capacity_pow2,
+            buckets: (0..num_buckets)
+                .map(|_| PageTableBucket::default())
+                .collect(),
+            next_bucket: 0,
+        }
+    }
+
+    // high capacity_pow2 bits of the PageId select the bucket; the
+    // remaining low bits are the offset within it
+    fn bucket_and_offset(&self, pid: PageId) -> (usize, usize) {
+        let bucket_bits = self.capacity_pow2;
+        let offset_bits = (std::mem::size_of::<PageId>() as u32 * 8) - bucket_bits;
+        let bucket = (pid >> offset_bits) as usize;
+        let offset = (pid & ((1 << offset_bits) - 1)) as usize;
+        (bucket, offset)
+    }
+
+    fn get(&self, pid: PageId) -> Option<usize> {
+        let (bucket, offset) = self.bucket_and_offset(pid);
+        self.buckets[bucket].pointers.get(offset).copied().flatten()
+    }
+
+    fn set(&mut self, pid: PageId, ptr: usize) {
+        let (bucket, offset) = self.bucket_and_offset(pid);
+        let pointers = &mut self.buckets[bucket].pointers;
+        if offset >= pointers.len() {
+            pointers.resize(offset + 1, None);
+        }
+        pointers[offset] = Some(ptr);
+    }
+
+    /// Allocates the next `PageId`, rotating across buckets so that no
+    /// single bucket grows much faster than the others.
+    fn alloc_page(&mut self) -> PageId {
+        let bucket = self.next_bucket;
+        self.next_bucket = (self.next_bucket + 1) % self.buckets.len();
+
+        let offset = self.buckets[bucket].pointers.len();
+        self.buckets[bucket].pointers.push(None);
+
+        let offset_bits = (std::mem::size_of::<PageId>() as u32 * 8)
+            - self.capacity_pow2;
+        ((bucket as PageId) << offset_bits) | offset as PageId
+    }
+
+    /// Per-bucket occupied-slot counts, for the cache manager to weigh
+    /// eviction/admission decisions against.
+    fn occupancy(&self) -> Vec<usize> {
+        self.buckets
+            .iter()
+            .map(|b| b.pointers.iter().filter(|p| p.is_some()).count())
+            .collect()
+    }
+
+    /// Doubles the bucket count by splitting each existing bucket in
+    /// two along its next high bit, re-homing entries into the new
+    /// bucket layout. This is a re-shard, not a realloc of one big Vec.
+    fn grow(&mut self) {
+        let mut grown = PageTable::new(self.capacity_pow2 + 1);
+        for (bucket_idx, bucket) in self.buckets.iter().enumerate() {
+            for (offset, ptr) in bucket.pointers.iter().enumerate() {
+                if let Some(ptr) = ptr {
+                    let offset_bits = (std::mem::size_of::<PageId>() as u32
+                        * 8)
+                        - self.capacity_pow2;
+                    let pid = ((bucket_idx as PageId) << offset_bits)
+                        | offset as PageId;
+                    grown.set(pid, *ptr);
+                }
+            }
+        }
+        *self = grown;
+    }
+}
+
+/// `TxId` -> (lastLSN, committed?) built by the analysis pass.
+#[derive(Debug, Clone, Copy)]
+struct TxTableEntry {
+    last_lsn: Lsn,
+    committed: bool,
+}
+
+/// The outcome of the analysis pass: which pages need redoing from
+/// where, and which transactions are winners/losers.
+#[derive(Debug, Default)]
+struct RecoveryState {
+    // PageId -> recLSN, the first LSN known to have dirtied the page
+    // since it was last flushed
+    dirty_page_table: std::collections::BTreeMap<PageId, Lsn>,
+    transaction_table: std::collections::BTreeMap<TxId, TxTableEntry>,
+}
+
+// 4-bit saturating counters per row, 4 independent hash functions
+const CM_SKETCH_DEPTH: usize = 4;
+// the sketch is aged (halved) once total increments cross
+// capacity * CM_SKETCH_RESET_FACTOR
+const CM_SKETCH_RESET_FACTOR: u64 = 10;
+
+/// An approximate PageId -> access-frequency counter, used to decide
+/// whether an incoming page deserves to evict one that's already
+/// cached. Counters are 4-bit and saturating, packed two per byte, and
+/// are periodically halved so frequency estimates track recent access
+/// patterns rather than all-time totals.
+#[derive(Debug)]
+struct CountMinSketch {
+    rows: [Vec<u8>; CM_SKETCH_DEPTH],
+    width: usize,
+    total_increments: u64,
+    reset_threshold: u64,
+}
+
+impl CountMinSketch {
+    fn new(capacity: usize) -> CountMinSketch {
+        let width = capacity.next_power_of_two().max(16);
+        let bytes = width / 2;
+        CountMinSketch {
+            rows: [
+                vec![0u8; bytes],
+                vec![0u8; bytes],
+                vec![0u8; bytes],
+                vec![0u8; bytes],
+            ],
+            width,
+            total_increments: 0,
+            reset_threshold: capacity as u64 * CM_SKETCH_RESET_FACTOR,
+        }
+    }
+
+    // (byte index, true if the high nibble of that byte) for a given
+    // row's hash of `pid`
+    fn slot(&self, row: usize, pid: PageId) -> (usize, bool) {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (row, pid).hash(&mut hasher);
+        let idx = (hasher.finish() as usize) & (self.width - 1);
+        (idx / 2, idx % 2 == 0)
+    }
+
+    fn increment(&mut self, pid: PageId) {
+        for row in 0..CM_SKETCH_DEPTH {
+            let (byte_idx, hi_nibble) = self.slot(row, pid);
+            let byte = &mut self.rows[row][byte_idx];
+            let counter = if hi_nibble { *byte >> 4 } else { *byte & 0x0F };
+            if counter < 15 {
+                *byte = if hi_nibble {
+                    ((counter + 1) << 4) | (*byte & 0x0F)
+                } else {
+                    (*byte & 0xF0) | (counter + 1)
+                };
+            }
+        }
+        self.total_increments += 1;
+        self.maybe_age();
+    }
+
+    fn estimate(&self, pid: PageId) -> u8 {
+        (0..CM_SKETCH_DEPTH)
+            .map(|row| {
+                let (byte_idx, hi_nibble) = self.slot(row, pid);
+                let byte = self.rows[row][byte_idx];
+                if hi_nibble { byte >> 4 } else { byte & 0x0F }
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn maybe_age(&mut self) {
+        if self.total_increments < self.reset_threshold {
+            return;
+        }
+        for row in self.rows.iter_mut() {
+            for byte in row.iter_mut() {
+                let hi = (*byte >> 4) >> 1;
+                let lo = (*byte & 0x0F) >> 1;
+                *byte = (hi << 4) | lo;
+            }
+        }
+        self.total_increments /= 2;
+    }
+}
+
+/// A bloom filter that suppresses "one-hit wonders" from being
+/// admitted into the main cache region on their first appearance: a
+/// page must be seen at least twice (once to set the bit, once to
+/// observe it already set) before it's treated as having a real
+/// frequency signal.
+#[derive(Debug)]
+struct Doorkeeper {
+    bits: Vec<u64>,
+    len_bits: usize,
+}
+
+impl Doorkeeper {
+    fn new(capacity: usize) -> Doorkeeper {
+        let len_bits = (capacity * 8).next_power_of_two().max(64);
+        Doorkeeper { bits: vec![0u64; len_bits / 64], len_bits }
+    }
+
+    fn hash(&self, pid: PageId, seed: u8) -> usize {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (pid, seed).hash(&mut hasher);
+        (hasher.finish() as usize) & (self.len_bits - 1)
+    }
+
+    // marks pid as seen, returning whether it had already been seen
+    fn check_and_set(&mut self, pid: PageId) -> bool {
+        let mut already_set = true;
+        for seed in 0..2u8 {
+            let bit = self.hash(pid, seed);
+            let (word, offset) = (bit / 64, bit % 64);
+            if self.bits[word] & (1 << offset) == 0 {
+                already_set = false;
+            }
+            self.bits[word] |= 1 << offset;
+        }
+        already_set
+    }
+}
+
+/// w-TinyLFU admission/eviction: an LRU "window" absorbs bursts of new
+/// pages, and a segmented-LRU "main" region (80% protected / 20%
+/// probation) holds pages worth keeping, admitted from the window by
+/// Count-Min sketch frequency.
+#[derive(Debug)]
+struct TinyLfuCache {
+    sketch: CountMinSketch,
+    doorkeeper: Doorkeeper,
+
+    window: VecDeque<PageId>,
+    window_capacity: usize,
+
+    probation: VecDeque<PageId>,
+    protected: VecDeque<PageId>,
+    probation_capacity: usize,
+    protected_capacity: usize,
+}
+
+impl TinyLfuCache {
+    fn new(capacity: usize) -> TinyLfuCache {
+        let window_capacity = std::cmp::max(1, capacity / 100);
+        let main_capacity = capacity - window_capacity;
+        let protected_capacity = main_capacity * 80 / 100;
+        let probation_capacity = main_capacity - protected_capacity;
+
+        TinyLfuCache {
+            sketch: CountMinSketch::new(capacity),
+            doorkeeper: Doorkeeper::new(capacity),
+            window: VecDeque::new(),
+            window_capacity,
+            probation: VecDeque::new(),
+            protected: VecDeque::new(),
+            probation_capacity,
+            protected_capacity,
+        }
+    }
+
+    /// Records a hit against an already-cached page, bumping its
+    /// frequency and promoting it within the SLRU.
+    fn record_access(&mut self, pid: PageId) {
+        self.sketch.increment(pid);
+
+        if let Some(pos) = self.window.iter().position(|&p| p == pid) {
+            self.window.remove(pos);
+            self.window.push_back(pid);
+            return;
+        }
+        if let Some(pos) = self.protected.iter().position(|&p| p == pid) {
+            self.protected.remove(pos);
+            self.protected.push_back(pid);
+            return;
+        }
+        if let Some(pos) = self.probation.iter().position(|&p| p == pid) {
+            self.probation.remove(pos);
+            self.promote_to_protected(pid);
+        }
+    }
+
+    fn promote_to_protected(&mut self, pid: PageId) {
+        self.protected.push_back(pid);
+        if self.protected.len() > self.protected_capacity {
+            let demoted = self.protected.pop_front().unwrap();
+            self.probation.push_back(demoted);
+        }
+    }
+
+    /// Admits a newly faulted-in page into the window, returning the
+    /// `PageId` (if any) that should actually be evicted from the
+    /// heap-backed buffer pool to make room.
+    fn insert(&mut self, pid: PageId) -> Option<PageId> {
+        // only bump the frequency estimate once the doorkeeper has seen
+        // this page before, so a single one-off fault-in can't look as
+        // hot as a page that's actually been accessed repeatedly
+        if self.doorkeeper.check_and_set(pid) {
+            self.sketch.increment(pid);
+        }
+
+        self.window.push_back(pid);
+        if self.window.len() <= self.window_capacity {
+            return None;
+        }
+
+        let window_victim = self.window.pop_front().unwrap();
+
+        if self.probation.len() + self.protected.len()
+            < self.probation_capacity + self.protected_capacity
+        {
+            self.probation.push_back(window_victim);
+            return None;
+        }
+
+        // the main region's would-be victim is the LRU end of
+        // probation; protected pages have already earned re-promotion
+        // and are left alone
+        let main_victim = match self.probation.front().copied() {
+            Some(pid) => pid,
+            None => return Some(window_victim),
+        };
+
+        if self.sketch.estimate(window_victim) > self.sketch.estimate(main_victim)
+        {
+            self.probation.pop_front();
+            self.probation.push_back(window_victim);
+            Some(main_victim)
+        } else {
+            Some(window_victim)
+        }
+    }
 }
 
 #[derive(Debug)]
 struct BufferPool {
     next_tx: TxId,
-    next_page: PageId,
+    // next LSN to stamp on an appended log record
+    next_lsn: Lsn,
     free_pages: Vec<PageId>,
     log: File,
     heap: File,
-    page_pointers: Vec<usize>,
+    // sharded PageId -> heap pointer directory; also owns next_page
+    // allocation, rotated across buckets
+    page_pointers: PageTable,
+    // w-TinyLFU admission/eviction over the cached pages
+    cache: TinyLfuCache,
     buffer_pool_size: usize,
     buffer_pool_pointers: [*mut libc::c_void; SIZE_CLASSES],
+    // base of the RESERVED_HEAP_BYTES virtual address reservation that
+    // the file-backed heap mapping lives in; stable for the life of the
+    // process so swizzled `Pointer`s into it never dangle across growth
+    heap_base: *mut libc::c_void,
+    // how much of the reservation is currently mapped to live heap file
+    // bytes, starting at heap_base
+    heap_mapped_len: usize,
+    // next free byte offset to bump-allocate a freshly flushed page at,
+    // per size class; there's no freelist yet, so pages are never
+    // reclaimed once written
+    buffer_pool_cursors: [usize; SIZE_CLASSES],
+    // PageId -> (size class, offset within that class's arena, length),
+    // so fault-in knows where a page's bytes live given just its id
+    page_slots: std::collections::BTreeMap<PageId, (usize, usize, usize)>,
+    // pageLSN of the last write to each page, checked by recovery's redo
+    // pass to decide whether a logged update still needs replaying
+    page_lsns: std::collections::BTreeMap<PageId, Lsn>,
+    // resident, decompressed [keys | values] payloads, keyed by PageId;
+    // this is what a ValueRef's Arc<[u8]> points into
+    page_cache: std::collections::BTreeMap<PageId, Arc<[u8]>>,
+    // each cached page's key/value-length directory, kept alongside
+    // page_cache so a lookup doesn't have to re-decompress to find
+    // where a value starts
+    page_directories: std::collections::BTreeMap<PageId, (Vec<u64>, Vec<u64>)>,
 }
 
 #[derive(Debug)]
 struct Db {
     buffer_pool: BufferPool,
+    compression: CompressionType,
+    // stand-in for a real B+Tree root until `traverse` exists: the one
+    // page `set` ever writes, holding exactly one key/value pair
+    root_page_id: Option<PageId>,
 }
 
 impl Db {
     fn set(&mut self, key: &[u8], value: &[u8]) {
-        todo!()
+        let pid = match self.root_page_id {
+            Some(pid) => pid,
+            None => {
+                let pid = self.buffer_pool.page_pointers.alloc_page();
+                self.root_page_id = Some(pid);
+                pid
+            }
+        };
+
+        let lsn = self.buffer_pool.append_update(
+            pid,
+            PageUpdateOwned::Set { key: key.to_vec(), value: value.to_vec() },
+            PageUpdateOwned::Del { key: key.to_vec() },
+        );
+        self.buffer_pool.set_page_lsn(pid, lsn);
+
+        // no lo/hi boundary keys on this single-page stand-in, one child
+        let key_lengths = [0u64, 0u64, key.len() as u64];
+        let val_lengths = [value.len() as u64];
+
+        let mut raw_keys_and_values = Vec::with_capacity(key.len() + value.len());
+        raw_keys_and_values.extend_from_slice(key);
+        raw_keys_and_values.extend_from_slice(value);
+
+        self.flush_page(pid, true, &key_lengths, &val_lengths, &raw_keys_and_values);
     }
 
-    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        todo!()
+    fn get(&mut self, key: &[u8]) -> Option<ValueRef> {
+        let pid = self.root_page_id?;
+        let page = self.buffer_pool.fault_in(pid)?.ok()?;
+        let (key_lengths, val_lengths) =
+            self.buffer_pool.page_directories.get(&pid)?;
+
+        // one child, so its key immediately follows lo/hi and its value
+        // immediately follows all keys
+        let key_start = (key_lengths[0] + key_lengths[1]) as usize;
+        let key_len = key_lengths[2] as usize;
+        if &page[key_start..key_start + key_len] != key {
+            return None;
+        }
+        let val_start = key_start + key_len;
+        let val_len = val_lengths[0] as usize;
+
+        Some(ValueRef { page, start: val_start, end: val_start + val_len })
     }
 
     fn traverse(&self, key: &[u8]) -> &'static Page {
         todo!()
     }
+
+    // serializes a page - header, uncompressed key/value-length
+    // directory, then the (possibly compressed) [keys | values] region
+    // and its CRC32 - and writes it into the size-classed buffer pool
+    // arena that fits it
+    fn flush_page(
+        &mut self,
+        pid: PageId,
+        is_leaf: bool,
+        key_lengths: &[u64],
+        val_lengths: &[u64],
+        raw_keys_and_values: &[u8],
+    ) {
+        self.buffer_pool.write_page(
+            pid,
+            is_leaf,
+            key_lengths,
+            val_lengths,
+            raw_keys_and_values,
+            self.compression,
+        );
+    }
+
+    /// Walks every page in the heap and every record in the log,
+    /// validating their stored CRC32s, and returns the pages/records
+    /// that failed rather than panicking or returning bad bytes.
+    fn verify(&self) -> VerifyReport {
+        todo!("walk buffer_pool.heap page-by-page and buffer_pool.log record-by-record, comparing stored vs recomputed CRC32s")
+    }
+
+    /// Rebuilds a heap page that failed its checksum from the log's
+    /// redo chain, if the log still holds the committed updates that
+    /// produced it. This is the same replay `BufferPool::redo_pass`
+    /// does at startup, scoped to a single page.
+    fn repair_page(&mut self, pid: PageId) -> Result<(), DbError> {
+        todo!("replay pid's redo chain from the log, as in BufferPool::redo_pass")
+    }
+}
+
+/// The outcome of `Db::verify()`: every page and log record whose
+/// stored checksum didn't match what was recomputed from its bytes.
+#[derive(Debug, Default)]
+struct VerifyReport {
+    corrupt_pages: Vec<PageId>,
+    corrupt_log_records: Vec<Lsn>,
 }
 
 impl BufferPool {
@@ -224,23 +1180,65 @@ impl BufferPool {
         let buffer_pool_size =
             std::cmp::max(64 * 1024, cache_size_in_bytes.next_power_of_two());
 
+        // .truncate(false) is explicit, not the default behavior it
+        // happens to match: recover() needs whatever a previous process
+        // already appended to "log" still to be there, and "heap"'s live
+        // prefix is re-derived from its on-disk length just below.
         let log = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open("log")
             .unwrap();
 
-        // TODO todo!("find the lowest stable point in the log");
-
         let heap = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open("heap")
             .unwrap();
 
-        // TODO todo!("replay the log into the heap");
+        use std::os::unix::io::AsRawFd;
+
+        // reserve a large, never-moving virtual address range up front
+        let heap_base = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                RESERVED_HEAP_BYTES,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANON,
+                -1,
+                0,
+            )
+        };
+        if heap_base.is_null() || heap_base == libc::MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            panic!("failed to reserve heap address space: {:?}", err);
+        }
+
+        let heap_len = heap.metadata().unwrap().len() as usize;
+        let heap_mapped_len = std::cmp::max(heap_len, 64 * 1024);
+        heap.set_len(heap_mapped_len as u64).unwrap();
+
+        // map the live prefix of the heap file directly over the start
+        // of the reservation; reads are then served from these bytes
+        // with no intermediate copy into a Vec
+        let mapped = unsafe {
+            mmap(
+                heap_base,
+                heap_mapped_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                heap.as_raw_fd(),
+                0,
+            )
+        };
+        if mapped.is_null() || mapped == libc::MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            panic!("failed to map heap file: {:?}", err);
+        }
 
         let mut buffer_pool_pointers = [std::ptr::null_mut(); SIZE_CLASSES];
 
@@ -262,16 +1260,606 @@ impl BufferPool {
             buffer_pool_pointers[idx] = ptr;
         }
 
-        dbg!(BufferPool {
+        let cache_capacity =
+            std::cmp::max(1, buffer_pool_size / ESTIMATED_PAGE_SIZE);
+
+        let mut buffer_pool = BufferPool {
             next_tx: 0,
-            next_page: 0,
-            page_pointers: vec![],
+            next_lsn: 0,
+            page_pointers: PageTable::new(INITIAL_PAGE_TABLE_BUCKETS_POW2),
+            cache: TinyLfuCache::new(cache_capacity),
             free_pages: vec![],
             log,
             heap,
             buffer_pool_size,
             buffer_pool_pointers,
-        })
+            heap_base,
+            heap_mapped_len,
+            buffer_pool_cursors: [0; SIZE_CLASSES],
+            page_slots: std::collections::BTreeMap::new(),
+            page_lsns: std::collections::BTreeMap::new(),
+            page_cache: std::collections::BTreeMap::new(),
+            page_directories: std::collections::BTreeMap::new(),
+        };
+
+        // replay the log's analysis/redo/undo passes before handing the
+        // pool back to the caller, so a crash mid-write is fixed up here
+        // instead of surfacing as corruption later
+        buffer_pool.recover();
+
+        dbg!(buffer_pool)
+    }
+
+    /// Full ARIES analysis/redo/undo recovery, run once at open. The
+    /// analysis pass starts from the most recent checkpoint (the log's
+    /// "lowest stable point"), so a long-running log doesn't need a
+    /// full scan.
+    fn recover(&mut self) {
+        let (checkpoint_lsn, mut state) = self.last_checkpoint();
+        let records = self.read_log_records_from(checkpoint_lsn);
+
+        self.analysis_pass(&records, &mut state);
+        self.redo_pass(&records, &state);
+        self.undo_pass(&records, &state);
+    }
+
+    /// Locate the most recent `Checkpoint` record, returning the LSN to
+    /// resume analysis from plus the DPT/transaction table it captured.
+    /// With no checkpoint yet, recovery falls back to scanning the
+    /// whole log from LSN 0 with empty tables.
+    fn last_checkpoint(&self) -> (Lsn, RecoveryState) {
+        // a checkpoint's own LSN is always younger than any record it
+        // summarizes, so scanning the whole log for the one with the
+        // largest LSN is equivalent to following a "last checkpoint"
+        // pointer without needing to maintain one
+        let newest = self
+            .read_log_records_from(0)
+            .into_iter()
+            .filter_map(|record| match record {
+                LogRecordOwned::Checkpoint { lsn, dirty_page_table, transaction_table, .. } => {
+                    Some((lsn, dirty_page_table, transaction_table))
+                }
+                _ => None,
+            })
+            .max_by_key(|(lsn, ..)| *lsn);
+
+        match newest {
+            Some((lsn, dirty_page_table, transaction_table)) => {
+                let mut state = RecoveryState::default();
+                state.dirty_page_table = dirty_page_table.into_iter().collect();
+                state.transaction_table = transaction_table
+                    .into_iter()
+                    .map(|(tx, last_lsn, committed)| {
+                        (tx, TxTableEntry { last_lsn, committed })
+                    })
+                    .collect();
+                (lsn, state)
+            }
+            None => (0, RecoveryState::default()),
+        }
+    }
+
+    /// Read every `LogRecord` at or after `from_lsn`, in LSN order,
+    /// verifying each one's stored CRC32 against its payload as it's
+    /// deserialized. A record that fails is dropped from the replay
+    /// set: an incomplete write at the tail of the log looks exactly
+    /// like a checksum mismatch, and both mean "this record never
+    /// made it to disk".
+    fn read_log_records_from(&self, from_lsn: Lsn) -> Vec<LogRecordOwned> {
+        let mut log_file = &self.log;
+        log_file.seek(SeekFrom::Start(0)).expect("seek log");
+        let mut bytes = Vec::new();
+        log_file.read_to_end(&mut bytes).expect("read log");
+
+        let mut records = Vec::new();
+        let mut offset = 0usize;
+
+        while offset + 4 <= bytes.len() {
+            let payload_len = read_u32_le(&bytes, offset) as usize;
+            offset += 4;
+
+            if offset + payload_len + 4 > bytes.len() {
+                // an incomplete trailing write - the process crashed
+                // mid-append
+                break;
+            }
+
+            let payload = &bytes[offset..offset + payload_len];
+            offset += payload_len;
+            let stored_checksum = read_u32_le(&bytes, offset);
+            offset += 4;
+
+            let mut hasher = Hasher::new();
+            hasher.update(payload);
+            if hasher.finalize() != stored_checksum {
+                continue;
+            }
+
+            if let Some(record) = LogRecordOwned::deserialize(payload, stored_checksum) {
+                if record.lsn() >= from_lsn {
+                    records.push(record);
+                }
+            }
+        }
+
+        records
+    }
+
+    /// Analysis: build up the transaction table and dirty page table by
+    /// replaying the log's bookkeeping, without touching the heap.
+    fn analysis_pass(
+        &self,
+        records: &[LogRecordOwned],
+        state: &mut RecoveryState,
+    ) {
+        for record in records {
+            match record {
+                LogRecordOwned::Update { lsn, tx, pid, .. }
+                | LogRecordOwned::Clr { lsn, tx, pid, .. } => {
+                    state.dirty_page_table.entry(*pid).or_insert(*lsn);
+                    state
+                        .transaction_table
+                        .entry(*tx)
+                        .and_modify(|e| e.last_lsn = *lsn)
+                        .or_insert(TxTableEntry {
+                            last_lsn: *lsn,
+                            committed: false,
+                        });
+                }
+                LogRecordOwned::Commit { tx, last_lsn, .. } => {
+                    state
+                        .transaction_table
+                        .entry(*tx)
+                        .and_modify(|e| {
+                            e.last_lsn = *last_lsn;
+                            e.committed = true;
+                        })
+                        .or_insert(TxTableEntry {
+                            last_lsn: *last_lsn,
+                            committed: true,
+                        });
+                }
+                LogRecordOwned::Checkpoint { .. } => {}
+            }
+        }
+    }
+
+    /// Redo: replay every update whose page might not have made it to
+    /// the heap before the crash. Starts from the minimum recLSN in the
+    /// dirty page table, since nothing before that could possibly need
+    /// redoing.
+    fn redo_pass(&mut self, records: &[LogRecordOwned], state: &RecoveryState) {
+        let min_rec_lsn = match state.dirty_page_table.values().min() {
+            Some(lsn) => *lsn,
+            None => return,
+        };
+
+        for record in records {
+            let (lsn, pid, redo) = match record {
+                LogRecordOwned::Update { lsn, pid, redo, .. } => {
+                    (*lsn, *pid, redo)
+                }
+                LogRecordOwned::Clr { lsn, pid, undo, .. } => {
+                    (*lsn, *pid, undo)
+                }
+                LogRecordOwned::Commit { .. }
+                | LogRecordOwned::Checkpoint { .. } => continue,
+            };
+
+            if lsn < min_rec_lsn {
+                continue;
+            }
+
+            match state.dirty_page_table.get(&pid) {
+                Some(rec_lsn) if lsn >= *rec_lsn => {}
+                _ => continue,
+            }
+
+            if self.page_lsn(pid) < lsn {
+                self.apply_page_update(pid, redo);
+                self.set_page_lsn(pid, lsn);
+            }
+        }
+    }
+
+    /// Undo: roll back every update made by a transaction that never
+    /// committed ("loser"), walking each loser's `previous_lsn` chain
+    /// in descending LSN order and writing a CLR for each undone update
+    /// so that a second crash mid-undo can resume from `undo_next_lsn`
+    /// instead of redoing work.
+    fn undo_pass(&mut self, records: &[LogRecordOwned], state: &RecoveryState) {
+        let by_lsn: std::collections::BTreeMap<Lsn, &LogRecordOwned> =
+            records.iter().map(|r| (r.lsn(), r)).collect();
+
+        let losers: Vec<TxId> = state
+            .transaction_table
+            .iter()
+            .filter(|(_, entry)| !entry.committed)
+            .map(|(tx, _)| *tx)
+            .collect();
+
+        for tx in losers {
+            let mut cursor = state.transaction_table[&tx].last_lsn;
+
+            while cursor != 0 {
+                let record = match by_lsn.get(&cursor) {
+                    Some(record) => *record,
+                    None => break,
+                };
+
+                let (pid, undo, previous_lsn) = match record {
+                    LogRecordOwned::Update {
+                        pid,
+                        undo,
+                        previous_lsn,
+                        ..
+                    } => (*pid, undo, *previous_lsn),
+                    LogRecordOwned::Clr {
+                        pid, undo_next_lsn, ..
+                    } => {
+                        cursor = *undo_next_lsn;
+                        continue;
+                    }
+                    LogRecordOwned::Commit { .. }
+                    | LogRecordOwned::Checkpoint { .. } => break,
+                };
+
+                self.apply_page_update(pid, undo);
+                self.append_clr(tx, pid, undo, previous_lsn);
+
+                cursor = previous_lsn;
+            }
+        }
+    }
+
+    /// The `pageLSN` of the last write to `pid`, used to decide whether
+    /// a redo is still needed. Tracked alongside the page directory
+    /// rather than in the page header itself, since pages in the
+    /// buffer-pool arenas are bump-allocated and never rewritten in
+    /// place - a page's header always reflects the write that produced
+    /// it, not a separately-stamped pageLSN.
+    fn page_lsn(&self, pid: PageId) -> Lsn {
+        self.page_lsns.get(&pid).copied().unwrap_or(0)
+    }
+
+    fn set_page_lsn(&mut self, pid: PageId, lsn: Lsn) {
+        self.page_lsns.insert(pid, lsn);
+    }
+
+    /// Applies a redo/undo `PageUpdate` to the live page at `pid`.
+    /// Always writes the page uncompressed: recovery runs before any
+    /// `Db` exists to supply a `CompressionType`, and the codec only
+    /// affects footprint, never correctness.
+    fn apply_page_update(&mut self, pid: PageId, update: &PageUpdateOwned) {
+        match update {
+            PageUpdateOwned::Set { key, value } => {
+                // no lo/hi boundary keys on this single-page stand-in,
+                // one child - mirrors Db::set
+                let key_lengths = [0u64, 0u64, key.len() as u64];
+                let val_lengths = [value.len() as u64];
+
+                let mut raw = Vec::with_capacity(key.len() + value.len());
+                raw.extend_from_slice(key);
+                raw.extend_from_slice(value);
+
+                self.write_page(
+                    pid,
+                    true,
+                    &key_lengths,
+                    &val_lengths,
+                    &raw,
+                    CompressionType::None,
+                );
+            }
+            PageUpdateOwned::Del { .. } => {
+                // no on-disk tombstone format exists yet; dropping the
+                // resident copy is enough, since nothing currently reads
+                // a deleted page back out
+                self.page_cache.remove(&pid);
+                self.page_directories.remove(&pid);
+            }
+        }
+    }
+
+    // serializes and appends one already-built record to the log file
+    fn append_log_record(&mut self, record: &LogRecordOwned) {
+        debug_assert!(record.verify_checksum());
+        self.log.write_all(&record.serialize()).expect("log write failed");
+    }
+
+    /// Appends an `Update` record for a single-statement transaction,
+    /// immediately followed by its `Commit` - this crate has no
+    /// multi-statement transactions yet, so every write commits as soon
+    /// as it's logged. Returns the update's LSN, for the caller to stamp
+    /// via `set_page_lsn` on the page it's about to write.
+    fn append_update(
+        &mut self,
+        pid: PageId,
+        redo: PageUpdateOwned,
+        undo: PageUpdateOwned,
+    ) -> Lsn {
+        let tx = self.next_tx;
+        self.next_tx += 1;
+
+        let lsn = self.next_lsn;
+        self.next_lsn += 1;
+
+        let mut record = LogRecordOwned::Update {
+            lsn,
+            tx,
+            pid,
+            redo,
+            undo,
+            previous_lsn: 0,
+            checksum: 0,
+        };
+        let checksum = record.compute_checksum();
+        if let LogRecordOwned::Update { checksum: c, .. } = &mut record {
+            *c = checksum;
+        }
+        self.append_log_record(&record);
+
+        let mut commit = LogRecordOwned::Commit { tx, last_lsn: lsn, checksum: 0 };
+        let checksum = commit.compute_checksum();
+        if let LogRecordOwned::Commit { checksum: c, .. } = &mut commit {
+            *c = checksum;
+        }
+        self.append_log_record(&commit);
+
+        lsn
+    }
+
+    /// Append a compensation log record for an undone update.
+    fn append_clr(
+        &mut self,
+        tx: TxId,
+        pid: PageId,
+        undo: &PageUpdateOwned,
+        undo_next_lsn: Lsn,
+    ) {
+        let lsn = self.next_lsn;
+        self.next_lsn += 1;
+
+        let mut record = LogRecordOwned::Clr {
+            lsn,
+            tx,
+            pid,
+            undo: undo.clone(),
+            undo_next_lsn,
+            checksum: 0,
+        };
+        let checksum = record.compute_checksum();
+        if let LogRecordOwned::Clr { checksum: c, .. } = &mut record {
+            *c = checksum;
+        }
+
+        self.append_log_record(&record);
+    }
+
+    /// Write a fuzzy checkpoint: a snapshot of the current dirty page
+    /// table and transaction table. The checkpoint's LSN becomes the
+    /// new "lowest stable point" that the next recovery's analysis pass
+    /// can start from, so the whole log need not be rescanned.
+    fn checkpoint(&mut self, state: &RecoveryState) {
+        let lsn = self.next_lsn;
+        self.next_lsn += 1;
+
+        let dirty_page_table =
+            state.dirty_page_table.iter().map(|(pid, lsn)| (*pid, *lsn)).collect();
+        let transaction_table = state
+            .transaction_table
+            .iter()
+            .map(|(tx, entry)| (*tx, entry.last_lsn, entry.committed))
+            .collect();
+
+        let mut record = LogRecordOwned::Checkpoint {
+            lsn,
+            dirty_page_table,
+            transaction_table,
+            checksum: 0,
+        };
+        let checksum = record.compute_checksum();
+        if let LogRecordOwned::Checkpoint { checksum: c, .. } = &mut record {
+            *c = checksum;
+        }
+
+        self.append_log_record(&record);
+    }
+
+    // extend the live heap mapping to cover at least `new_len` bytes of
+    // the heap file, growing the file itself as needed. because the
+    // reservation made in `open` is RESERVED_HEAP_BYTES wide, this only
+    // ever extends the mapping in place over already-reserved address
+    // space and never moves `heap_base`.
+    fn grow_heap(&mut self, new_len: usize) {
+        if new_len <= self.heap_mapped_len {
+            return;
+        }
+        assert!(
+            new_len <= RESERVED_HEAP_BYTES,
+            "heap grew past the {} byte reservation",
+            RESERVED_HEAP_BYTES
+        );
+
+        use std::os::unix::io::AsRawFd;
+
+        self.heap.set_len(new_len as u64).unwrap();
+
+        let growth_base =
+            unsafe { (self.heap_base as *mut u8).add(self.heap_mapped_len) };
+        let growth_len = new_len - self.heap_mapped_len;
+
+        let mapped = unsafe {
+            mmap(
+                growth_base as *mut libc::c_void,
+                growth_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                self.heap.as_raw_fd(),
+                self.heap_mapped_len as libc::off_t,
+            )
+        };
+        if mapped.is_null() || mapped == libc::MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            panic!("failed to grow heap mapping: {:?}", err);
+        }
+
+        self.heap_mapped_len = new_len;
+    }
+
+    // the bytes of a fault-in'd page, read directly out of the
+    // file-backed mapping with no intermediate copy
+    fn heap_slice(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(offset + len <= self.heap_mapped_len);
+        unsafe {
+            std::slice::from_raw_parts(
+                (self.heap_base as *const u8).add(offset),
+                len,
+            )
+        }
+    }
+
+    // picks the smallest SIZE_CLASSES bucket whose arena fits `len`
+    // bytes; the arenas are allocated by `BufferPool::open` as
+    // `buffer_pool_pointers`
+    fn size_class_for(len: usize) -> usize {
+        for class in 0..SIZE_CLASSES {
+            if (64 * 1024) << class >= len {
+                return class;
+            }
+        }
+        SIZE_CLASSES - 1
+    }
+
+    // serializes a page - header, uncompressed key/value-length
+    // directory, then the (possibly compressed) [keys | values] region
+    // and its CRC32 - and writes it into the size-classed buffer pool
+    // arena that fits it
+    fn write_page(
+        &mut self,
+        pid: PageId,
+        is_leaf: bool,
+        key_lengths: &[u64],
+        val_lengths: &[u64],
+        raw_keys_and_values: &[u8],
+        compression: CompressionType,
+    ) {
+        let compressed = compression.compress(raw_keys_and_values);
+
+        let mut tail = Vec::new();
+        for len in key_lengths {
+            tail.extend_from_slice(&len.to_le_bytes());
+        }
+        for len in val_lengths {
+            tail.extend_from_slice(&len.to_le_bytes());
+        }
+        tail.extend_from_slice(&compressed);
+
+        let mut hasher = Hasher::new();
+        hasher.update(&tail);
+        let checksum = hasher.finalize();
+
+        let child_count = val_lengths.len() as u32;
+        let key_length_sum: u32 = key_lengths[2..].iter().sum::<u64>() as u32;
+
+        let mut page_bytes = Vec::with_capacity(17 + tail.len());
+        page_bytes.push(if is_leaf { 0 } else { 1 });
+        page_bytes.extend_from_slice(&child_count.to_le_bytes()[..3]);
+        page_bytes.extend_from_slice(&key_length_sum.to_le_bytes());
+        page_bytes.push(compression.tag());
+        page_bytes
+            .extend_from_slice(&(raw_keys_and_values.len() as u32).to_le_bytes());
+        page_bytes.extend_from_slice(&checksum.to_le_bytes());
+        page_bytes.extend_from_slice(&tail);
+
+        self.write_page_to_pool(pid, &page_bytes);
+
+        // the page moved; drop any stale decompressed copy
+        self.page_cache.remove(&pid);
+        self.page_directories.remove(&pid);
+    }
+
+    // bump-allocates space in the size class fitting `page_bytes` and
+    // copies it in, recording where fault_in can find it again
+    fn write_page_to_pool(&mut self, pid: PageId, page_bytes: &[u8]) {
+        let class = BufferPool::size_class_for(page_bytes.len());
+        let cursor = self.buffer_pool_cursors[class];
+        let new_cursor = cursor + page_bytes.len();
+        assert!(
+            new_cursor <= self.buffer_pool_size,
+            "size class {} arena exhausted ({} of {} bytes)",
+            class,
+            new_cursor,
+            self.buffer_pool_size,
+        );
+
+        let dst =
+            unsafe { (self.buffer_pool_pointers[class] as *mut u8).add(cursor) };
+        unsafe {
+            std::ptr::copy_nonoverlapping(page_bytes.as_ptr(), dst, page_bytes.len())
+        };
+
+        self.buffer_pool_cursors[class] = new_cursor;
+        self.page_slots.insert(pid, (class, cursor, page_bytes.len()));
+    }
+
+    // the bytes of a fault-in'd page, read directly out of the
+    // size-classed arena it was written into, with no intermediate copy
+    fn pool_slice(&self, class: usize, offset: usize, len: usize) -> &[u8] {
+        assert!(offset + len <= self.buffer_pool_size);
+        unsafe {
+            std::slice::from_raw_parts(
+                (self.buffer_pool_pointers[class] as *const u8).add(offset),
+                len,
+            )
+        }
+    }
+
+    // reads a page back out of its buffer pool arena by its page_slots
+    // entry, verifies its checksum, decompresses it, and caches the
+    // resulting payload + directory so repeat lookups don't pay for
+    // decompression (or checksum verification) again
+    fn fault_in(&mut self, pid: PageId) -> Option<Result<Arc<[u8]>, DbError>> {
+        if let Some(cached) = self.page_cache.get(&pid).cloned() {
+            self.touch_page(pid);
+            return Some(Ok(cached));
+        }
+
+        let (class, offset, len) = *self.page_slots.get(&pid)?;
+        let page_bytes = self.pool_slice(class, offset, len);
+        let page: &Page =
+            unsafe { std::mem::transmute((page_bytes.as_ptr(), page_bytes.len())) };
+        let view = match page.view(pid) {
+            Ok(view) => view,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let payload: Arc<[u8]> = view.payload.into();
+        self.page_directories.insert(pid, (view.key_lengths, view.val_lengths));
+        self.page_cache.insert(pid, payload.clone());
+
+        // evict whichever page w-TinyLFU decided to make room for this
+        // one; its bytes stay resident in the buffer pool arena, only
+        // the decompressed, cached copy is dropped
+        if let Some(victim) = self.admit_page(pid) {
+            self.page_cache.remove(&victim);
+            self.page_directories.remove(&victim);
+        }
+        Some(Ok(payload))
+    }
+
+    /// Called on every cache hit, to keep the w-TinyLFU frequency
+    /// estimate and SLRU position up to date.
+    fn touch_page(&mut self, pid: PageId) {
+        self.cache.record_access(pid);
+    }
+
+    /// Called when a page is freshly faulted in from its buffer pool
+    /// arena. Runs it through w-TinyLFU admission, returning a page
+    /// whose decompressed, cached copy should now be evicted (if
+    /// admission pushed one out) - callers are expected to act on it.
+    fn admit_page(&mut self, pid: PageId) -> Option<PageId> {
+        self.cache.insert(pid)
     }
 }
 
@@ -286,17 +1874,114 @@ impl Drop for BufferPool {
                 eprintln!("failed to unmap memory: {:?}", err);
             }
         }
+
+        let ret = unsafe { munmap(self.heap_base, RESERVED_HEAP_BYTES) };
+        if ret != 0 {
+            let err = std::io::Error::last_os_error();
+            eprintln!("failed to unmap heap reservation: {:?}", err);
+        }
     }
 }
 
 fn open(cache_size_in_bytes: usize) -> Db {
+    open_with_compression(cache_size_in_bytes, CompressionType::None)
+}
+
+fn open_with_compression(
+    cache_size_in_bytes: usize,
+    compression: CompressionType,
+) -> Db {
     let buffer_pool = BufferPool::open(cache_size_in_bytes);
-    Db { buffer_pool }
+    Db { buffer_pool, compression, root_page_id: None }
 }
 
 fn main() {
     let mut db = open(1024 * 1024);
 
     db.set(b"a", b"a");
-    assert_eq!(db.get(b"a").unwrap(), vec![b'a']);
+    assert_eq!(&*db.get(b"a").unwrap(), b"a");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_table_round_trips_every_allocated_id() {
+        let mut table = PageTable::new(2);
+        let mut ids = vec![];
+        for i in 0..16 {
+            let pid = table.alloc_page();
+            table.set(pid, i * 100);
+            ids.push(pid);
+        }
+        for (i, pid) in ids.into_iter().enumerate() {
+            assert_eq!(table.get(pid), Some(i * 100));
+        }
+    }
+
+    #[test]
+    fn page_table_alloc_rotates_across_buckets() {
+        let mut table = PageTable::new(2);
+        let buckets: Vec<usize> = (0..4)
+            .map(|_| {
+                let pid = table.alloc_page();
+                table.bucket_and_offset(pid).0
+            })
+            .collect();
+        assert_eq!(buckets, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn page_table_grow_preserves_existing_pointers() {
+        let mut table = PageTable::new(2);
+        let ids: Vec<PageId> = (0..8)
+            .map(|i| {
+                let pid = table.alloc_page();
+                table.set(pid, i * 10);
+                pid
+            })
+            .collect();
+
+        table.grow();
+
+        for (i, pid) in ids.into_iter().enumerate() {
+            assert_eq!(table.get(pid), Some(i * 10));
+        }
+    }
+
+    #[test]
+    fn page_table_get_of_unset_id_is_none() {
+        let table = PageTable::new(2);
+        assert_eq!(table.get(0), None);
+    }
+
+    #[test]
+    fn count_min_sketch_estimate_of_unseen_is_zero() {
+        let sketch = CountMinSketch::new(1024);
+        assert_eq!(sketch.estimate(42), 0);
+    }
+
+    #[test]
+    fn count_min_sketch_increment_raises_estimate() {
+        let mut sketch = CountMinSketch::new(1024);
+        sketch.increment(7);
+        assert!(sketch.estimate(7) >= 1);
+    }
+
+    #[test]
+    fn count_min_sketch_counters_saturate_at_15() {
+        let mut sketch = CountMinSketch::new(1024);
+        for _ in 0..100 {
+            sketch.increment(7);
+        }
+        assert_eq!(sketch.estimate(7), 15);
+    }
+
+    #[test]
+    fn doorkeeper_requires_two_sightings() {
+        let mut doorkeeper = Doorkeeper::new(1024);
+        assert_eq!(doorkeeper.check_and_set(99), false);
+        assert_eq!(doorkeeper.check_and_set(99), true);
+    }
 }